@@ -3,10 +3,15 @@
 pub mod builder;
 mod cpu;
 mod mem;
+pub mod monitor;
+pub mod signal;
 mod x86_64;
 
-pub use builder::Builder;
-use cpu::{Allocator, Cpu};
+pub use builder::{Builder, KeepConfig};
+pub use monitor::Monitor;
+pub use signal::SignalGuard;
+
+use cpu::{Allocator, Cpu, CpuStatus};
 use mem::Region;
 
 use crate::backend::kvm::vm::mem::KvmUserspaceMemoryRegion;
@@ -17,6 +22,11 @@ use anyhow::Result;
 use kvm_bindings::KVM_MAX_CPUID_ENTRIES;
 use kvm_ioctls::{Kvm, VmFd};
 
+/// IRQ line used to forward a host SIGUSR-class signal into the guest;
+/// a legacy PIC/IOAPIC line clear of the PIT (IRQ 0) and keyboard (IRQ 1).
+const HOST_SIGNAL_IRQ: u32 = 9;
+
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 
 pub struct VirtualMachine {
@@ -26,11 +36,63 @@ pub struct VirtualMachine {
     regions: Vec<Region>,
     shim_entry: PhysAddr,
     shim_start: PhysAddr,
+    /// Ceiling from the keep's `[keep] max_memory` manifest entry, if any.
+    max_memory: Option<u64>,
+    /// Whether the host can honor a guest-programmed TSC-deadline timer,
+    /// as opposed to relying solely on the in-kernel PIT's periodic tick.
+    tsc_deadline_timer: bool,
+    /// Liveness records for `monitor::Monitor`'s `query-cpus`; one entry
+    /// per vCPU ever created by `add_thread`.
+    cpus: Vec<CpuStatus>,
+    /// Set by `request_stop` (directly, or via `signal::SignalGuard` on
+    /// SIGTERM/SIGINT/SIGHUP) and polled by each `Cpu`'s run loop.
+    stop_requested: Arc<AtomicBool>,
+}
+
+impl VirtualMachine {
+    /// Whether `Cpu::calibrate_tsc` can calibrate vCPUs' virtual TSC so
+    /// the guest's own `IA32_TSC_DEADLINE` MSR can be a timer source,
+    /// instead of relying solely on the in-kernel PIT's periodic tick.
+    pub fn tsc_deadline_timer_supported(&self) -> bool {
+        self.tsc_deadline_timer
+    }
+
+    /// Asks every vCPU to exit its `KVM_RUN` loop as soon as it next
+    /// checks in, so regions can drop in a deterministic order instead
+    /// of by luck of process teardown.
+    pub fn request_stop(&self) {
+        self.stop_requested.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stop_requested(&self) -> bool {
+        self.stop_requested.load(Ordering::Relaxed)
+    }
+
+    /// Pulses `HOST_SIGNAL_IRQ` so the shim sees a defined interrupt for
+    /// a forwarded host signal (e.g. SIGUSR1), rather than the guest
+    /// just disappearing along with the host process.
+    pub fn forward_signal_interrupt(&self) -> Result<()> {
+        self.fd.set_irq_line(HOST_SIGNAL_IRQ, true)?;
+        self.fd.set_irq_line(HOST_SIGNAL_IRQ, false)?;
+        Ok(())
+    }
 }
 
 impl VirtualMachine {
     pub fn add_memory(&mut self, pages: u64) -> Result<i64> {
-        let mem_size = pages * 4096;
+        let mem_size = pages
+            .checked_mul(4096)
+            .ok_or_else(|| anyhow::anyhow!("{pages} pages overflows a byte count"))?;
+
+        if let Some(max_memory) = self.max_memory {
+            let current: u64 = self.regions.iter().map(|r| r.as_guest().count).sum();
+            if current + mem_size > max_memory {
+                anyhow::bail!(
+                    "growing by {mem_size} bytes would exceed the keep's max_memory of {max_memory} bytes"
+                );
+            }
+        }
+
         let last_region = self.regions.last().unwrap().as_guest();
 
         let guest_addr_start = unsafe {
@@ -82,15 +144,75 @@ impl Keep for RwLock<VirtualMachine> {
             regs.rsi = keep.shim_start.as_u64();
             regs.rdi = &prefix.shared_pages[0] as *const _ as u64 - address_space.start.as_u64();
         } else {
-            unimplemented!()
+            // The AP itself gets its `cs:rip` from KVM's in-kernel SIPI
+            // handling (see `cpu::Cpu`); we just need to leave it a
+            // stack and an id to find its own data with once it lands.
+            if id >= x86_64::MAX_CPUS {
+                anyhow::bail!("vCPU id {id} is past the {} vCPUs this backend supports", x86_64::MAX_CPUS);
+            }
+            let stack = &prefix.ap_stacks[id];
+            let stack_top = stack as *const _ as u64 + stack.len() as u64;
+            regs.rsp = stack_top - address_space.start.as_u64();
+            regs.rdi = id as u64;
         }
 
         vcpu.set_regs(&regs)?;
         vcpu.set_cpuid2(&keep.kvm.get_supported_cpuid(KVM_MAX_CPUID_ENTRIES)?)?;
 
         let cr3 = &*prefix.pml4t as *const _ as u64 - address_space.start.as_u64();
-
-        let thread = Cpu::new(vcpu, id, self.clone(), keep.shim_entry, cr3)?;
+        // `Cpu::new` programs this straight into the BSP's `rip`, so it
+        // has to be the guest-physical address the shim was loaded at,
+        // not the host-virtual one `Builder::provision` tracks it as.
+        let shim_entry = PhysAddr::new(keep.shim_entry.as_u64() - address_space.start.as_u64());
+        let tsc_deadline_timer = keep.tsc_deadline_timer;
+
+        let halted = Arc::new(AtomicBool::new(false));
+        keep.cpus.push(CpuStatus {
+            id,
+            halted: halted.clone(),
+        });
+
+        let thread = Cpu::new(
+            vcpu,
+            id,
+            self.clone(),
+            shim_entry,
+            cr3,
+            tsc_deadline_timer,
+            halted,
+        )?;
         Ok(Box::new(thread))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Requires `/dev/kvm`; skipped rather than failed when unavailable
+    /// so `cargo test` stays green on hosts without virtualization.
+    #[test]
+    fn add_memory_rejects_growth_past_max_memory() {
+        if std::fs::metadata("/dev/kvm").is_err() {
+            eprintln!("skipping add_memory_rejects_growth_past_max_memory: no /dev/kvm");
+            return;
+        }
+
+        let config = KeepConfig::from_toml(
+            r#"
+            [keep]
+            memory = "16MiB"
+            max_memory = "20MiB"
+            "#,
+        )
+        .unwrap();
+
+        let keep = Builder::new(&[], &[0xf4], config).unwrap().build();
+        let mut vm = keep.write().unwrap();
+
+        // 20MiB max_memory - 16MiB initial region leaves 4MiB of
+        // headroom, i.e. 1024 pages; one more should tip over the edge.
+        let err = vm.add_memory(1025).unwrap_err();
+        assert!(err.to_string().contains("would exceed the keep's max_memory"));
+    }
+}
@@ -0,0 +1,194 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! The `[keep]`/`[cpu]` manifest that drives `Builder`, the same way a
+//! `vore` TOML config drives a QEMU instance.
+//!
+//! ```toml
+//! [keep]
+//! memory = "512MiB"
+//! max_memory = "4GiB"
+//!
+//! [cpu]
+//! count = 1
+//! ```
+
+use super::super::x86_64::MAX_CPUS;
+
+use std::fmt;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct KeepConfig {
+    pub keep: KeepSection,
+    #[serde(default)]
+    pub cpu: CpuSection,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeepSection {
+    #[serde(deserialize_with = "deserialize_size")]
+    pub memory: u64,
+    #[serde(default, deserialize_with = "deserialize_opt_size")]
+    pub max_memory: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CpuSection {
+    #[serde(default = "default_cpu_count")]
+    pub count: u32,
+}
+
+impl Default for CpuSection {
+    fn default() -> Self {
+        Self {
+            count: default_cpu_count(),
+        }
+    }
+}
+
+fn default_cpu_count() -> u32 {
+    1
+}
+
+impl KeepConfig {
+    pub fn from_toml(manifest: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(manifest)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reject configurations this backend can never honor, so failure
+    /// happens at parse time rather than deep inside `Builder`.
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.cpu.count == 0 {
+            anyhow::bail!("`cpu.count` must be at least 1");
+        }
+
+        // `x86_64::MAX_CPUS` is this backend's own ceiling (one AP boot
+        // stack per possible vCPU, carved out of region zero's `Prefix`),
+        // which is the real limit `add_thread` can honor — not KVM's
+        // much larger per-VM vCPU cap.
+        if self.cpu.count as usize > MAX_CPUS {
+            anyhow::bail!(
+                "`cpu.count` of {} exceeds the {} vCPUs this backend supports",
+                self.cpu.count,
+                MAX_CPUS
+            );
+        }
+
+        if let Some(max_memory) = self.keep.max_memory {
+            if max_memory < self.keep.memory {
+                anyhow::bail!(
+                    "`keep.max_memory` ({} bytes) is smaller than `keep.memory` ({} bytes)",
+                    max_memory,
+                    self.keep.memory
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("size `{value}` is missing a unit, e.g. `512MiB`"))?;
+    let (digits, unit) = value.split_at(split);
+
+    let base: u64 = digits
+        .parse()
+        .map_err(|_| format!("`{digits}` is not a valid size"))?;
+
+    let multiplier: u64 = match unit.trim() {
+        "B" => 1,
+        "KiB" => 1024,
+        "MiB" => 1024 * 1024,
+        "GiB" => 1024 * 1024 * 1024,
+        other => return Err(format!("unknown size unit `{other}`, expected one of B/KiB/MiB/GiB")),
+    };
+
+    base.checked_mul(multiplier)
+        .ok_or_else(|| format!("size `{value}` overflows a u64"))
+}
+
+struct SizeVisitor;
+
+impl<'de> Visitor<'de> for SizeVisitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a size string like \"512MiB\"")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        parse_size(value).map_err(de::Error::custom)
+    }
+}
+
+fn deserialize_size<'de, D: Deserializer<'de>>(de: D) -> Result<u64, D::Error> {
+    de.deserialize_str(SizeVisitor)
+}
+
+fn deserialize_opt_size<'de, D: Deserializer<'de>>(de: D) -> Result<Option<u64>, D::Error> {
+    Ok(Some(deserialize_size(de)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_rejects_missing_unit() {
+        assert!(parse_size("512").unwrap_err().contains("missing a unit"));
+    }
+
+    #[test]
+    fn parse_size_rejects_bad_digits() {
+        assert!(parse_size("abcMiB").unwrap_err().contains("not a valid size"));
+    }
+
+    #[test]
+    fn parse_size_rejects_overflow() {
+        assert!(parse_size("18446744073709551615GiB")
+            .unwrap_err()
+            .contains("overflows a u64"));
+    }
+
+    #[test]
+    fn parse_size_parses_mib_and_gib() {
+        assert_eq!(parse_size("512MiB").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_size("4GiB").unwrap(), 4 * 1024 * 1024 * 1024);
+    }
+
+    #[test]
+    fn validate_rejects_zero_cpus() {
+        let err = KeepConfig::from_toml("[keep]\nmemory = \"16MiB\"\n\n[cpu]\ncount = 0\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("`cpu.count` must be at least 1"));
+    }
+
+    #[test]
+    fn validate_rejects_cpu_count_over_max() {
+        let manifest = format!(
+            "[keep]\nmemory = \"16MiB\"\n\n[cpu]\ncount = {}\n",
+            MAX_CPUS + 1
+        );
+        let err = KeepConfig::from_toml(&manifest).unwrap_err();
+        assert!(err.to_string().contains("exceeds the"));
+    }
+
+    #[test]
+    fn validate_rejects_max_memory_below_memory() {
+        let err = KeepConfig::from_toml(
+            "[keep]\nmemory = \"16MiB\"\nmax_memory = \"8MiB\"\n",
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("smaller than `keep.memory`"));
+    }
+}
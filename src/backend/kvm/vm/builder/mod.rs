@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Builds a [`VirtualMachine`] from a code/shim pair and a [`KeepConfig`]
+//! manifest, so a keep's resource shape is reproducible from a file
+//! instead of hard-coded in `Cpu`/`Region` setup.
+
+mod config;
+
+pub use config::KeepConfig;
+
+use super::cpu::Allocator;
+use super::mem::{KvmUserspaceMemoryRegion, Region};
+use super::x86_64::Prefix;
+use super::VirtualMachine;
+
+use ::x86_64::PhysAddr;
+use anyhow::Result;
+use kvm_bindings::kvm_pit_config;
+use kvm_ioctls::{Cap, Kvm, VmFd};
+
+use std::sync::{Arc, RwLock};
+
+pub struct Builder {
+    kvm: Kvm,
+    fd: VmFd,
+    config: KeepConfig,
+    regions: Vec<Region>,
+    shim_entry: PhysAddr,
+    shim_start: PhysAddr,
+    max_memory: Option<u64>,
+    tsc_deadline_timer: bool,
+}
+
+impl Builder {
+    pub fn new(code: &[u8], shim: &[u8], config: KeepConfig) -> Result<Self> {
+        let kvm = Kvm::new()?;
+        let fd = kvm.create_vm()?;
+
+        // An in-kernel LAPIC is what lets `cpu::Cpu` bring up application
+        // processors by having the BSP's shim send them INIT/SIPI,
+        // rather than the host hand-rolling that sequence.
+        fd.create_irq_chip()?;
+
+        // An in-kernel PIT gives the guest a periodic tick routed straight
+        // into the LAPIC without a host round-trip for every interrupt.
+        fd.create_pit2(kvm_pit_config::default())?;
+
+        // The alternative to the PIT's periodic tick is a free-running
+        // TSC-deadline timer the guest programs itself; only offer it if
+        // the host can actually honor it.
+        let tsc_deadline_timer = kvm.check_extension(Cap::TscDeadlineTimer);
+
+        let max_memory = config.keep.max_memory;
+
+        let mut builder = Self {
+            kvm,
+            fd,
+            config,
+            regions: Vec::new(),
+            shim_entry: PhysAddr::new(0),
+            shim_start: PhysAddr::new(0),
+            max_memory,
+            tsc_deadline_timer,
+        };
+
+        builder.provision(code, shim)?;
+        Ok(builder)
+    }
+
+    /// Lays down region zero according to `self.config.keep.memory`,
+    /// replacing what used to be an implicit single-region bootstrap.
+    fn provision(&mut self, code: &[u8], shim: &[u8]) -> Result<()> {
+        let mem_size = self.config.keep.memory as usize;
+
+        let prefix_len = std::mem::size_of::<Prefix>();
+        let required = prefix_len + shim.len() + code.len();
+        if mem_size < required {
+            anyhow::bail!(
+                "`keep.memory` of {mem_size} bytes is too small to hold the \
+                 {prefix_len}-byte prefix plus a {}-byte shim and {}-byte code \
+                 ({required} bytes required)",
+                shim.len(),
+                code.len()
+            );
+        }
+
+        let guest_addr_start = unsafe {
+            mmap::map(
+                0,
+                mem_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                None,
+                0,
+            )?
+        };
+        let unmap = unsafe {
+            mmap::Unmap::new(lset::Span {
+                start: guest_addr_start,
+                count: mem_size,
+            })
+        };
+
+        let region = KvmUserspaceMemoryRegion {
+            slot: 0,
+            flags: 0,
+            guest_phys_addr: 0,
+            memory_size: mem_size as _,
+            userspace_addr: guest_addr_start as _,
+        };
+
+        unsafe {
+            self.fd.set_user_memory_region(region)?;
+        }
+
+        let shim_start = guest_addr_start as u64 + prefix_len as u64;
+        let shim_load_addr = shim_start;
+        let code_load_addr = shim_load_addr + shim.len() as u64;
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                shim.as_ptr(),
+                shim_load_addr as *mut u8,
+                shim.len(),
+            );
+            std::ptr::copy_nonoverlapping(
+                code.as_ptr(),
+                code_load_addr as *mut u8,
+                code.len(),
+            );
+        }
+
+        self.shim_start = PhysAddr::new(shim_start);
+        self.shim_entry = PhysAddr::new(shim_start);
+        self.regions.push(Region::new(0, region, unmap));
+
+        Ok(())
+    }
+
+    pub fn cpu_count(&self) -> u32 {
+        self.config.cpu.count
+    }
+
+    pub fn build(self) -> Arc<RwLock<VirtualMachine>> {
+        Arc::new(RwLock::new(VirtualMachine {
+            kvm: self.kvm,
+            fd: self.fd,
+            id_alloc: Allocator::default(),
+            regions: self.regions,
+            shim_entry: self.shim_entry,
+            shim_start: self.shim_start,
+            max_memory: self.max_memory,
+            tsc_deadline_timer: self.tsc_deadline_timer,
+            cpus: Vec::new(),
+            stop_requested: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        }))
+    }
+}
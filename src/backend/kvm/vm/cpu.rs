@@ -0,0 +1,276 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! The per-vCPU run loop and id allocation for a keep.
+//!
+//! vCPU 0 (the BSP) boots directly into the shim: KVM never runs a
+//! BIOS/real-mode stage for it, so `Cpu::new` has to land it in 64-bit
+//! long mode itself, with `cs:rip` pointed straight at `shim_entry`.
+//! Every other id is an application processor: with the in-kernel LAPIC
+//! enabled (see `Builder::new`), KVM itself walks a vCPU through
+//! `KVM_MP_STATE_UNINITIALIZED` -> `INIT_RECEIVED` -> `SIPI_RECEIVED` ->
+//! `RUNNABLE` as the BSP's shim sends it INIT/SIPI over the IPI
+//! mechanism, loading `cs:rip` from the SIPI vector itself. So unlike
+//! the BSP, an AP's `cs:rip` is never ours to set; all we have to do
+//! ahead of time is give it a stack, page tables, and an APIC id to
+//! land on.
+
+use super::VirtualMachine;
+use crate::backend::{Command, Thread};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use kvm_ioctls::{VcpuExit, VcpuFd};
+use x86_64::PhysAddr;
+
+/// Offset of the APIC ID register within `kvm_lapic_state::regs`.
+const APIC_REG_ID: usize = 0x20;
+
+// `cr0`/`cr4`/`efer` bits `Cpu::new` needs to take the BSP straight from
+// KVM's reset state into the 64-bit long mode the shim's entry point
+// expects.
+const CR0_PE: u64 = 1 << 0;
+const CR0_PG: u64 = 1 << 31;
+const CR4_PAE: u64 = 1 << 5;
+const EFER_LME: u64 = 1 << 8;
+const EFER_LMA: u64 = 1 << 10;
+
+/// Hands out monotonically increasing vCPU ids, starting at the BSP (`0`).
+#[derive(Default)]
+pub struct Allocator(usize);
+
+impl Allocator {
+    pub fn next(&mut self) -> usize {
+        let id = self.0;
+        self.0 += 1;
+        id
+    }
+}
+
+/// The liveness record the monitor socket (`monitor::Monitor`) reads to
+/// answer `query-cpus` without touching the `Cpu` itself.
+pub struct CpuStatus {
+    pub id: usize,
+    pub halted: Arc<AtomicBool>,
+}
+
+pub struct Cpu {
+    vcpu: VcpuFd,
+    id: usize,
+    keep: Arc<RwLock<VirtualMachine>>,
+    tsc_deadline_timer: bool,
+    halted: Arc<AtomicBool>,
+}
+
+impl Cpu {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        vcpu: VcpuFd,
+        id: usize,
+        keep: Arc<RwLock<VirtualMachine>>,
+        shim_entry: PhysAddr,
+        cr3: u64,
+        tsc_deadline_timer: bool,
+        halted: Arc<AtomicBool>,
+    ) -> Result<Self> {
+        let mut sregs = vcpu.get_sregs()?;
+        sregs.cr3 = cr3;
+        if id == 0 {
+            // The BSP has no BIOS to walk it from real mode up to the
+            // shim's native 64-bit long mode, so set that state up
+            // directly: paging (`cr3` above) plus protection, PAE, and
+            // long-mode enable, with `cs` switched to a 64-bit code
+            // segment. An AP never takes this branch — its `sregs` stay
+            // at the real-mode reset state until the SIPI it receives
+            // (see the module docs) hands it off to the shim's own
+            // trampoline.
+            sregs.cr0 |= CR0_PE | CR0_PG;
+            sregs.cr4 |= CR4_PAE;
+            sregs.efer |= EFER_LME | EFER_LMA;
+
+            sregs.cs.l = 1; // 64-bit code segment
+            sregs.cs.db = 0;
+            sregs.cs.s = 1;
+            sregs.cs.present = 1;
+            sregs.cs.dpl = 0;
+            sregs.cs.type_ = 0b1011; // execute/read, accessed
+
+            for seg in [
+                &mut sregs.ds,
+                &mut sregs.es,
+                &mut sregs.fs,
+                &mut sregs.gs,
+                &mut sregs.ss,
+            ] {
+                seg.l = 0;
+                seg.db = 1;
+                seg.s = 1;
+                seg.present = 1;
+                seg.dpl = 0;
+                seg.type_ = 0b0011; // read/write, accessed
+            }
+        }
+        vcpu.set_sregs(&sregs)?;
+
+        if id == 0 {
+            // Without this the BSP keeps whatever `rip` its reset state
+            // left it at, nowhere near the shim, and never executes a
+            // single shim instruction.
+            let mut regs = vcpu.get_regs()?;
+            regs.rip = shim_entry.as_u64();
+            vcpu.set_regs(&regs)?;
+        }
+
+        // Tag this vCPU's local APIC with its own id so IPIs (including
+        // the SIPI that wakes it, for id > 0) target the right core.
+        // The APIC ID register is a 4-byte little-endian field with the
+        // id in bits 24-31, not a single `regs` element.
+        let mut lapic = vcpu.get_lapic()?;
+        let id_bytes = ((id as u32) << 24).to_le_bytes();
+        for (reg, byte) in lapic.regs[APIC_REG_ID..APIC_REG_ID + 4]
+            .iter_mut()
+            .zip(id_bytes)
+        {
+            *reg = byte as i8;
+        }
+        vcpu.set_lapic(&lapic)?;
+
+        Ok(Self {
+            vcpu,
+            id,
+            keep,
+            tsc_deadline_timer,
+            halted,
+        })
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Calibrates this vCPU's virtual TSC frequency to `tsc_khz`, so the
+    /// clock the guest measures wall time against matches the host's.
+    /// Arming `IA32_TSC_DEADLINE` itself is guest-autonomous once KVM
+    /// hands the vCPU the TSC-deadline-timer CPUID feature bit (see
+    /// `set_cpuid2`); this just calibrates the TSC that MSR counts
+    /// down against, it doesn't arm anything itself. Errors if the host
+    /// never advertised `KVM_CAP_TSC_DEADLINE_TIMER`; the guest should
+    /// fall back to the in-kernel PIT's periodic tick in that case.
+    pub fn calibrate_tsc(&self, tsc_khz: u32) -> Result<()> {
+        if !self.tsc_deadline_timer {
+            anyhow::bail!(
+                "vcpu #{}: host does not support KVM_CAP_TSC_DEADLINE_TIMER",
+                self.id
+            );
+        }
+
+        self.vcpu.set_tsc_khz(tsc_khz)?;
+        Ok(())
+    }
+}
+
+impl Thread for Cpu {
+    fn enter(&mut self) -> Result<Command> {
+        loop {
+            if self.keep.read().unwrap().stop_requested() {
+                self.halted.store(true, Ordering::Relaxed);
+                return Ok(Command::Exit(0));
+            }
+
+            match self.vcpu.run()? {
+                VcpuExit::Hlt => {
+                    self.halted.store(true, Ordering::Relaxed);
+                    return Ok(Command::Exit(0));
+                }
+                VcpuExit::IoOut(..) | VcpuExit::IoIn(..) => continue,
+                exit => anyhow::bail!("unexpected vcpu #{} exit: {:?}", self.id, exit),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::kvm::vm::{Builder, KeepConfig};
+    use crate::backend::Keep;
+
+    use kvm_bindings::KVM_MP_STATE_UNINITIALIZED;
+
+    /// Requires `/dev/kvm`; skipped rather than failed when unavailable
+    /// so `cargo test` stays green on hosts without virtualization.
+    #[test]
+    fn smp_boot_reaches_shim_entry() {
+        if std::fs::metadata("/dev/kvm").is_err() {
+            eprintln!("skipping smp_boot_reaches_shim_entry: no /dev/kvm");
+            return;
+        }
+
+        let config = KeepConfig::from_toml(
+            r#"
+            [keep]
+            memory = "16MiB"
+
+            [cpu]
+            count = 2
+            "#,
+        )
+        .unwrap();
+
+        // A minimal shim: `hlt` in a loop, just enough for the BSP to
+        // reach an exit we recognize. It never touches the LAPIC, so it
+        // never sends an AP an INIT/SIPI — entering the AP the same way
+        // would block forever in KVM_MP_STATE_UNINITIALIZED with nothing
+        // left to wake it.
+        const SHIM: &[u8] = &[0xf4, 0xeb, 0xfd];
+        let code: &[u8] = &[];
+
+        let keep = Builder::new(code, SHIM, config).unwrap().build();
+
+        let mut cpu0 = keep.clone().add_thread().unwrap();
+
+        // Actually run the BSP: reaching the `hlt` is the observable
+        // signal that it made it to the shim entry point rather than,
+        // say, never having been scheduled at all.
+        assert!(matches!(cpu0.enter().unwrap(), Command::Exit(0)));
+
+        // The AP can't be driven through `enter()` without guest code to
+        // wake it (see above), so check only what `add_thread`/`Cpu::new`
+        // are actually responsible for on the host side: the vCPU is
+        // parked waiting for a SIPI, tagged with its own APIC id. Build
+        // it the same way `add_thread` would for the next id, without
+        // going through the `Keep`/`Thread` trait objects that would
+        // otherwise hide `Cpu`'s fields from this assertion.
+        let ap_id = 1;
+        let tsc_deadline_timer = keep.read().unwrap().tsc_deadline_timer_supported();
+        let vcpu = keep.read().unwrap().fd.create_vcpu(ap_id as _).unwrap();
+        let cpu1 = Cpu::new(
+            vcpu,
+            ap_id,
+            keep.clone(),
+            PhysAddr::new(0),
+            0,
+            tsc_deadline_timer,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(
+            cpu1.vcpu.get_mp_state().unwrap().mp_state,
+            KVM_MP_STATE_UNINITIALIZED
+        );
+        let lapic = cpu1.vcpu.get_lapic().unwrap();
+        let id_bytes: [u8; 4] = lapic.regs[APIC_REG_ID..APIC_REG_ID + 4]
+            .iter()
+            .map(|&b| b as u8)
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap();
+        assert_eq!(u32::from_le_bytes(id_bytes) >> 24, ap_id as u32);
+
+        let vm = keep.read().unwrap();
+        assert_eq!(vm.cpus.len(), 1);
+        assert!(vm.cpus[0].halted.load(Ordering::Relaxed));
+    }
+}
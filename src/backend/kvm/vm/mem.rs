@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tracking for a single `KVM_SET_USER_MEMORY_REGION` slot and the host
+//! mapping that backs it.
+
+use super::x86_64::Prefix;
+
+pub use kvm_bindings::kvm_userspace_memory_region as KvmUserspaceMemoryRegion;
+
+use ::x86_64::{PhysAddr, VirtAddr};
+
+/// A single guest-physical region, plus the host mapping that backs it.
+///
+/// The `mmap::Unmap` guard owns the host mapping and tears it down on
+/// drop, so a `Region` going out of scope is always safe even if the
+/// guest is still referencing the memory from the host's point of view.
+pub struct Region {
+    id: usize,
+    region: KvmUserspaceMemoryRegion,
+    unmap: mmap::Unmap,
+}
+
+impl Region {
+    pub fn new(id: usize, region: KvmUserspaceMemoryRegion, unmap: mmap::Unmap) -> Self {
+        Self { id, region, unmap }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn slot(&self) -> u32 {
+        self.region.slot
+    }
+
+    /// The region as seen by the guest: a span of guest-physical memory.
+    pub fn as_guest(&self) -> lset::Span<PhysAddr, u64> {
+        lset::Span {
+            start: PhysAddr::new(self.region.guest_phys_addr),
+            count: self.region.memory_size,
+        }
+    }
+
+    /// The region as mapped into the host's address space.
+    pub fn as_virt(&self) -> lset::Span<VirtAddr, u64> {
+        lset::Span {
+            start: VirtAddr::new(self.region.userspace_addr),
+            count: self.region.memory_size,
+        }
+    }
+
+    /// Region zero starts with a `Prefix` shared with the shim; callers
+    /// are responsible for only calling this on that region.
+    pub fn prefix_mut(&self) -> &mut Prefix {
+        unsafe { &mut *(self.region.userspace_addr as *mut Prefix) }
+    }
+}
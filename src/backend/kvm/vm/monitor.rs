@@ -0,0 +1,277 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! A QMP-style control socket for a running keep.
+//!
+//! Modeled on `vore`'s use of the QMP/qapi control channel: a Unix
+//! socket speaking one JSON request per line, one JSON response per
+//! line, funneling every live mutation through the same
+//! `Arc<RwLock<VirtualMachine>>` that `Keep::add_thread` and
+//! `VirtualMachine::add_memory` already use.
+
+use super::VirtualMachine;
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum Request {
+    QueryRegions,
+    AddMemory { pages: u64 },
+    QueryCpus,
+    Quit,
+}
+
+/// Owns the monitor socket's listening thread; dropping it tears the
+/// socket down.
+pub struct Monitor {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Monitor {
+    /// Binds `path` and starts accepting connections in a background
+    /// thread. Each connection is handled on its own thread, so a slow
+    /// or wedged client can't block other monitor commands.
+    pub fn spawn(keep: Arc<RwLock<VirtualMachine>>, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = stop.clone();
+            std::thread::spawn(move || Self::accept_loop(listener, keep, stop))
+        };
+
+        Ok(Self {
+            path,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    fn accept_loop(listener: UnixListener, keep: Arc<RwLock<VirtualMachine>>, stop: Arc<AtomicBool>) {
+        while !stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _addr)) => {
+                    let keep = keep.clone();
+                    let stop = stop.clone();
+                    std::thread::spawn(move || {
+                        if let Err(e) = Self::handle_connection(stream, &keep, &stop) {
+                            eprintln!("kvm monitor connection error: {e}");
+                        }
+                    });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn handle_connection(
+        stream: UnixStream,
+        keep: &Arc<RwLock<VirtualMachine>>,
+        stop: &Arc<AtomicBool>,
+    ) -> Result<()> {
+        let mut writer = stream.try_clone()?;
+        let reader = BufReader::new(stream);
+
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => Self::dispatch(request, keep, stop),
+                Err(e) => json!({ "error": e.to_string() }),
+            };
+
+            writeln!(writer, "{response}")?;
+
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn dispatch(request: Request, keep: &Arc<RwLock<VirtualMachine>>, stop: &Arc<AtomicBool>) -> Value {
+        match request {
+            Request::QueryRegions => {
+                let vm = keep.read().unwrap();
+                let regions: Vec<_> = vm
+                    .regions
+                    .iter()
+                    .map(|r| {
+                        let guest = r.as_guest();
+                        json!({
+                            "slot": r.slot(),
+                            "guest_phys_addr": guest.start.as_u64(),
+                            "size": guest.count,
+                        })
+                    })
+                    .collect();
+                json!({ "regions": regions })
+            }
+            Request::AddMemory { pages } => {
+                let mut vm = keep.write().unwrap();
+                // `add_memory` returns the host mmap address; callers
+                // asking "where did my memory land" want the new
+                // region's guest-physical address instead.
+                match vm.add_memory(pages) {
+                    Ok(_) => {
+                        let guest_addr = vm.regions.last().unwrap().as_guest().start.as_u64();
+                        json!({ "guest_addr": guest_addr })
+                    }
+                    Err(e) => json!({ "error": e.to_string() }),
+                }
+            }
+            Request::QueryCpus => {
+                let vm = keep.read().unwrap();
+                let cpus: Vec<_> = vm
+                    .cpus
+                    .iter()
+                    .map(|c| {
+                        json!({
+                            "id": c.id,
+                            "halted": c.halted.load(Ordering::Relaxed),
+                        })
+                    })
+                    .collect();
+                json!({ "cpus": cpus })
+            }
+            Request::Quit => {
+                // Mirror QMP's `quit`: tear down the keep along with the
+                // monitor, not just stop accepting new connections.
+                // `stop` also doubles as `accept_loop`'s run flag, so a
+                // lone `quit` already stops future connections; make the
+                // keep itself stop too rather than leaving it running
+                // headless.
+                keep.read().unwrap().request_stop();
+                stop.store(true, Ordering::Relaxed);
+                json!({ "ok": true })
+            }
+        }
+    }
+
+    /// Path of the bound Unix socket.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::kvm::vm::{Builder, KeepConfig};
+
+    /// Requires `/dev/kvm`; skipped rather than failed when unavailable
+    /// so `cargo test` stays green on hosts without virtualization.
+    #[test]
+    fn query_regions_and_add_memory_over_the_socket() {
+        if std::fs::metadata("/dev/kvm").is_err() {
+            eprintln!("skipping query_regions_and_add_memory_over_the_socket: no /dev/kvm");
+            return;
+        }
+
+        let config = KeepConfig::from_toml(
+            r#"
+            [keep]
+            memory = "16MiB"
+            "#,
+        )
+        .unwrap();
+
+        let keep = Builder::new(&[], &[0xf4], config).unwrap().build();
+
+        let path = std::env::temp_dir().join(format!(
+            "enarx-monitor-test-{}.sock",
+            std::process::id()
+        ));
+        let monitor = Monitor::spawn(keep, &path).unwrap();
+
+        let stream = UnixStream::connect(monitor.path()).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+
+        writeln!(writer, r#"{{"command":"query-regions"}}"#).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let response: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(response["regions"][0]["slot"], 0);
+        assert_eq!(response["regions"][0]["guest_phys_addr"], 0);
+
+        writeln!(writer, r#"{{"command":"add-memory","pages":1}}"#).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let response: Value = serde_json::from_str(&line).unwrap();
+        assert!(response["guest_addr"].is_number());
+    }
+
+    /// Requires `/dev/kvm`; skipped rather than failed when unavailable
+    /// so `cargo test` stays green on hosts without virtualization.
+    #[test]
+    fn quit_stops_both_the_monitor_and_the_keep() {
+        if std::fs::metadata("/dev/kvm").is_err() {
+            eprintln!("skipping quit_stops_both_the_monitor_and_the_keep: no /dev/kvm");
+            return;
+        }
+
+        let config = KeepConfig::from_toml(
+            r#"
+            [keep]
+            memory = "16MiB"
+            "#,
+        )
+        .unwrap();
+
+        let keep = Builder::new(&[], &[0xf4], config).unwrap().build();
+
+        let path = std::env::temp_dir().join(format!(
+            "enarx-monitor-quit-test-{}.sock",
+            std::process::id()
+        ));
+        let monitor = Monitor::spawn(keep.clone(), &path).unwrap();
+
+        let stream = UnixStream::connect(monitor.path()).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+
+        writeln!(writer, r#"{{"command":"quit"}}"#).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let response: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(response["ok"], true);
+
+        assert!(keep.read().unwrap().stop_requested());
+    }
+}
@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host-signal handling for a running keep, the way `pH` adopted
+//! `signal-hook` to coordinate shutdown instead of relying on `Drop`
+//! ordering picked by the kernel killing the process mid-syscall.
+//!
+//! SIGTERM/SIGINT/SIGHUP request a clean stop (`VirtualMachine::request_stop`,
+//! observed by every `Cpu`'s run loop); SIGUSR1 is forwarded into the
+//! guest as a defined interrupt instead.
+
+use super::VirtualMachine;
+
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+
+use anyhow::Result;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1};
+use signal_hook::iterator::{Handle, Signals};
+
+/// Installs the signal handlers for as long as it's held; dropping it
+/// uninstalls them and joins the handling thread.
+pub struct SignalGuard {
+    handle: Handle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SignalGuard {
+    pub fn install(keep: Arc<RwLock<VirtualMachine>>) -> Result<Self> {
+        let mut signals = Signals::new([SIGTERM, SIGINT, SIGHUP, SIGUSR1])?;
+        let handle = signals.handle();
+
+        let thread = std::thread::spawn(move || {
+            for signal in &mut signals {
+                match signal {
+                    SIGTERM | SIGINT | SIGHUP => {
+                        keep.read().unwrap().request_stop();
+                    }
+                    SIGUSR1 => {
+                        if let Err(e) = keep.read().unwrap().forward_signal_interrupt() {
+                            eprintln!("failed to forward SIGUSR1 into guest: {e}");
+                        }
+                    }
+                    _ => unreachable!("not one of the registered signals"),
+                }
+            }
+        });
+
+        Ok(Self {
+            handle,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        self.handle.close();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::kvm::vm::{Builder, KeepConfig};
+
+    use std::time::Duration;
+
+    /// Requires `/dev/kvm`; skipped rather than failed when unavailable
+    /// so `cargo test` stays green on hosts without virtualization.
+    #[test]
+    fn sigterm_requests_a_stop() {
+        if std::fs::metadata("/dev/kvm").is_err() {
+            eprintln!("skipping sigterm_requests_a_stop: no /dev/kvm");
+            return;
+        }
+
+        let config = KeepConfig::from_toml(
+            r#"
+            [keep]
+            memory = "16MiB"
+            "#,
+        )
+        .unwrap();
+
+        let keep = Builder::new(&[], &[0xf4], config).unwrap().build();
+        let guard = SignalGuard::install(keep.clone()).unwrap();
+
+        assert!(!keep.read().unwrap().stop_requested());
+
+        unsafe {
+            libc::raise(libc::SIGTERM);
+        }
+
+        // The signal is handled on SignalGuard's background thread, not
+        // synchronously with `raise`; give it a moment to run.
+        let mut waited = Duration::ZERO;
+        while !keep.read().unwrap().stop_requested() && waited < Duration::from_secs(1) {
+            std::thread::sleep(Duration::from_millis(10));
+            waited += Duration::from_millis(10);
+        }
+
+        assert!(keep.read().unwrap().stop_requested());
+
+        drop(guard);
+    }
+}
@@ -0,0 +1,30 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Guest-visible layout of the first region of a keep's address space.
+//!
+//! Region zero always begins with a `Prefix`: a page shared with the shim
+//! for bootstrap parameters, followed by the top-level page table the shim
+//! uses to identity-map the rest of the region.
+
+use x86_64::structures::paging::PageTable;
+
+/// Number of 4KiB pages reserved at the start of region zero for
+/// shim/host handoff data (currently just the boot info page).
+pub const NUM_SHARED_PAGES: usize = 1;
+
+/// How many application processors' stacks `Prefix` reserves room for.
+/// Bounds the SMP support in `cpu::Cpu`; raising it only costs guest
+/// address space, not host resources.
+pub const MAX_CPUS: usize = 64;
+
+/// Size, in bytes, of each application processor's boot stack.
+pub const AP_STACK_SIZE: usize = 4 * 4096;
+
+#[repr(C, align(4096))]
+pub struct Prefix {
+    pub shared_pages: [[u8; 4096]; NUM_SHARED_PAGES],
+    /// One boot stack per possible AP, carved out so `add_thread` never
+    /// has to grow region zero just to bring up another vCPU.
+    pub ap_stacks: [[u8; AP_STACK_SIZE]; MAX_CPUS],
+    pub pml4t: PageTable,
+}
@@ -11,6 +11,24 @@ use std::fs::File;
 use std::path::Path;
 use std::time::SystemTime;
 
+/// Warn on `epc_utilization` once free EPC drops below this percentage
+/// of the platform's reported ceiling, absent `MIN_FREE_EPC_PCT_VAR`.
+const DEFAULT_MIN_FREE_EPC_PCT: u8 = 15;
+
+/// Overrides [`DEFAULT_MIN_FREE_EPC_PCT`] when set to an integer in
+/// `0..=100`; anything else (unset, non-numeric, out of range) falls
+/// back to the default rather than erroring, since `epc_size` has no
+/// way to surface a bad-env-var failure to the caller.
+const MIN_FREE_EPC_PCT_VAR: &str = "ENARX_SGX_MIN_FREE_EPC_PCT";
+
+fn min_free_epc_pct() -> u8 {
+    std::env::var(MIN_FREE_EPC_PCT_VAR)
+        .ok()
+        .and_then(|v| v.trim().parse::<u8>().ok())
+        .filter(|pct| *pct <= 100)
+        .unwrap_or(DEFAULT_MIN_FREE_EPC_PCT)
+}
+
 use chrono::{DateTime, Local};
 use der::Decode;
 use serde_json::Value;
@@ -140,6 +158,7 @@ pub const CPUIDS: &[CpuId] = &[CpuId {
 pub fn epc_size(max: u32) -> Datum {
     let mut pass = false;
     let mut info = None;
+    let mut data = vec![];
 
     if max >= 0x00000012 {
         let mut size = 0;
@@ -158,6 +177,11 @@ pub fn epc_size(max: u32) -> Datum {
         let (n, s) = humanize(size as f64);
         info = Some(format!("{n:.0} {s}"));
         pass = true;
+
+        // Capability is static; these report whether that capacity is
+        // actually under pressure right now, and which CPUs back it.
+        data.push(epc_utilization(min_free_epc_pct()));
+        data.push(cpu_topology());
     }
 
     Datum {
@@ -165,10 +189,213 @@ pub fn epc_size(max: u32) -> Datum {
         mesg: None,
         pass,
         info,
+        data,
+    }
+}
+
+/// Reports how much of the platform's EPC ceiling is actually free right
+/// now, warning when it drops below `min_free_pct` — enclave page
+/// eviction under pressure silently tanks performance well before EPC
+/// is visibly exhausted.
+pub fn epc_utilization(min_free_pct: u8) -> Datum {
+    const NAME: &str = "EPC Utilization";
+    const NODE_DIR: &str = "/sys/devices/system/node";
+    // The only live EPC counter the upstream kernel actually exposes:
+    // the misc cgroup controller's "sgx_epc" resource, current usage in
+    // bytes, root-cgroup-wide. Per-node sysfs only ever reports the
+    // static `sgx_total_bytes` ceiling, never a live free/used count.
+    const MISC_CURRENT: &str = "/sys/fs/cgroup/misc.current";
+    const MISC_RESOURCE: &str = "sgx_epc";
+
+    let nodes = match std::fs::read_dir(NODE_DIR) {
+        Ok(nodes) => nodes,
+        Err(e) => {
+            return Datum {
+                name: NAME.into(),
+                pass: false,
+                info: None,
+                mesg: Some(format!("unable to read {NODE_DIR}: {e}")),
+                data: vec![],
+            }
+        }
+    };
+
+    let mut total_bytes = 0u64;
+    let mut saw_total = false;
+
+    for node in nodes.flatten() {
+        let sgx_dir = node.path().join("x86");
+
+        if let Some(bytes) = read_u64(&sgx_dir.join("sgx_total_bytes")) {
+            total_bytes += bytes;
+            saw_total = true;
+        }
+    }
+
+    if !saw_total {
+        return Datum {
+            name: NAME.into(),
+            pass: false,
+            info: None,
+            mesg: Some(format!(
+                "no `sgx_total_bytes` reported under {NODE_DIR}/*/x86"
+            )),
+            data: vec![],
+        };
+    }
+
+    // Not every kernel builds the misc cgroup's SGX EPC accounting
+    // (`CONFIG_CGROUP_MISC`); when it's missing we still report the
+    // ceiling but can't say anything about current pressure.
+    let used_bytes = match read_misc_current(Path::new(MISC_CURRENT), MISC_RESOURCE) {
+        Some(bytes) => bytes,
+        None => {
+            let (n, s) = humanize(total_bytes as f64);
+            return Datum {
+                name: NAME.into(),
+                pass: true,
+                info: Some(format!(
+                    "{n:.0} {s} total, live usage not reported by this kernel \
+                     (requires the misc cgroup's `{MISC_RESOURCE}` accounting)"
+                )),
+                mesg: None,
+                data: vec![],
+            };
+        }
+    };
+
+    let free_bytes = total_bytes.saturating_sub(used_bytes);
+    let free_pct = free_epc_pct(total_bytes, used_bytes);
+
+    let (used_n, used_s) = humanize(used_bytes as f64);
+    let (free_n, free_s) = humanize(free_bytes as f64);
+
+    let mesg = epc_pressure_mesg(free_pct, min_free_pct);
+
+    Datum {
+        name: NAME.into(),
+        pass: true,
+        info: Some(format!("{used_n:.0} {used_s} used, {free_n:.0} {free_s} free")),
+        mesg,
         data: vec![],
     }
 }
 
+/// Percentage of `total_bytes` still free, saturating rather than
+/// panicking if `used_bytes` somehow exceeds `total_bytes`.
+fn free_epc_pct(total_bytes: u64, used_bytes: u64) -> u8 {
+    if total_bytes == 0 {
+        return 100;
+    }
+
+    let free_bytes = total_bytes.saturating_sub(used_bytes);
+    (free_bytes.saturating_mul(100) / total_bytes) as u8
+}
+
+/// Warning message for `epc_utilization`, or `None` when `free_pct` is
+/// still at or above `min_free_pct`.
+fn epc_pressure_mesg(free_pct: u8, min_free_pct: u8) -> Option<String> {
+    (free_pct < min_free_pct).then(|| {
+        format!(
+            "free EPC is at {free_pct}% (below the {min_free_pct}% warning threshold); \
+             enclave pages may be under eviction pressure"
+        )
+    })
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path)
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Reads one resource's current usage out of a misc cgroup's
+/// `misc.current`, a `<resource> <bytes>` line per entry (see
+/// `Documentation/admin-guide/cgroup-v2.rst`).
+fn read_misc_current(path: &Path, resource: &str) -> Option<u64> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    contents.lines().find_map(|line| {
+        let (name, value) = line.split_once(char::is_whitespace)?;
+        (name == resource).then(|| value.trim().parse().ok())?
+    })
+}
+
+/// Reports per-logical-CPU topology (core id) and base frequency where
+/// the kernel exposes `cpufreq`, so `platform info` can explain an
+/// EPC-adjacent slowdown caused by thermal throttling rather than
+/// eviction. Each logical CPU is reported on its own, with no SMT
+/// sibling grouping — two hyperthreads on the same core show up as two
+/// entries sharing a `core_id`.
+pub fn cpu_topology() -> Datum {
+    const NAME: &str = "CPU Topology";
+    const CPU_DIR: &str = "/sys/devices/system/cpu";
+
+    let entries = match std::fs::read_dir(CPU_DIR) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Datum {
+                name: NAME.into(),
+                pass: false,
+                info: None,
+                mesg: Some(format!("unable to read {CPU_DIR}: {e}")),
+                data: vec![],
+            }
+        }
+    };
+
+    let mut cores = vec![];
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_cpu_dir = name.strip_prefix("cpu").is_some_and(|n| {
+            !n.is_empty() && n.chars().all(|c| c.is_ascii_digit())
+        });
+        if !is_cpu_dir {
+            continue;
+        }
+
+        let core_id = read_u64(&entry.path().join("topology/core_id"));
+        let base_freq_khz = read_u64(&entry.path().join("cpufreq/base_frequency"));
+
+        let info = match (core_id, base_freq_khz) {
+            (Some(core_id), Some(khz)) => {
+                Some(format!("core {core_id}, {:.2} GHz base", khz as f64 / 1e6))
+            }
+            (Some(core_id), None) => Some(format!("core {core_id}")),
+            (None, Some(khz)) => Some(format!("{:.2} GHz base", khz as f64 / 1e6)),
+            (None, None) => None,
+        };
+
+        cores.push(Datum {
+            pass: info.is_some(),
+            name,
+            info,
+            mesg: None,
+            data: vec![],
+        });
+    }
+
+    if cores.is_empty() {
+        return Datum {
+            name: NAME.into(),
+            pass: false,
+            info: None,
+            mesg: Some(format!("no CPU topology found under {CPU_DIR}")),
+            data: vec![],
+        };
+    }
+
+    Datum {
+        name: NAME.into(),
+        pass: true,
+        info: Some(format!("{} logical CPUs", cores.len())),
+        mesg: None,
+        data: cores,
+    }
+}
+
 pub fn dev_sgx_enclave() -> Datum {
     Datum {
         name: "Driver".into(),
@@ -449,3 +676,109 @@ pub fn tcb_fmspc_cached() -> Datum {
         data: vec![],
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_misc_current_parses_the_requested_resource() {
+        let path = std::env::temp_dir().join(format!(
+            "enarx-misc-current-test-{}-{}",
+            std::process::id(),
+            line!()
+        ));
+        std::fs::write(&path, "memory 4096\nsgx_epc 1048576\ntcp_mem 0\n").unwrap();
+
+        assert_eq!(read_misc_current(&path, "sgx_epc"), Some(1048576));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn read_misc_current_is_none_when_resource_is_absent() {
+        let path = std::env::temp_dir().join(format!(
+            "enarx-misc-current-test-{}-{}",
+            std::process::id(),
+            line!()
+        ));
+        std::fs::write(&path, "memory 4096\ntcp_mem 0\n").unwrap();
+
+        assert_eq!(read_misc_current(&path, "sgx_epc"), None);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn read_misc_current_is_none_on_malformed_value() {
+        let path = std::env::temp_dir().join(format!(
+            "enarx-misc-current-test-{}-{}",
+            std::process::id(),
+            line!()
+        ));
+        std::fs::write(&path, "sgx_epc not-a-number\n").unwrap();
+
+        assert_eq!(read_misc_current(&path, "sgx_epc"), None);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn read_misc_current_is_none_when_file_is_missing() {
+        let path = std::env::temp_dir().join(format!(
+            "enarx-misc-current-test-{}-does-not-exist",
+            std::process::id()
+        ));
+        assert_eq!(read_misc_current(&path, "sgx_epc"), None);
+    }
+
+    #[test]
+    fn free_epc_pct_computes_percentage_free() {
+        assert_eq!(free_epc_pct(1000, 0), 100);
+        assert_eq!(free_epc_pct(1000, 1000), 0);
+        assert_eq!(free_epc_pct(1000, 850), 15);
+    }
+
+    #[test]
+    fn free_epc_pct_is_100_when_total_is_zero() {
+        assert_eq!(free_epc_pct(0, 0), 100);
+    }
+
+    #[test]
+    fn free_epc_pct_saturates_when_used_exceeds_total() {
+        assert_eq!(free_epc_pct(1000, 2000), 0);
+    }
+
+    #[test]
+    fn epc_pressure_mesg_warns_below_threshold() {
+        assert!(epc_pressure_mesg(10, 15)
+            .unwrap()
+            .contains("free EPC is at 10%"));
+    }
+
+    #[test]
+    fn epc_pressure_mesg_is_none_at_or_above_threshold() {
+        assert!(epc_pressure_mesg(15, 15).is_none());
+        assert!(epc_pressure_mesg(50, 15).is_none());
+    }
+
+    #[test]
+    fn min_free_epc_pct_honors_the_env_var() {
+        std::env::set_var(MIN_FREE_EPC_PCT_VAR, "42");
+        assert_eq!(min_free_epc_pct(), 42);
+        std::env::remove_var(MIN_FREE_EPC_PCT_VAR);
+    }
+
+    #[test]
+    fn min_free_epc_pct_falls_back_on_bad_values() {
+        std::env::remove_var(MIN_FREE_EPC_PCT_VAR);
+        assert_eq!(min_free_epc_pct(), DEFAULT_MIN_FREE_EPC_PCT);
+
+        std::env::set_var(MIN_FREE_EPC_PCT_VAR, "not-a-number");
+        assert_eq!(min_free_epc_pct(), DEFAULT_MIN_FREE_EPC_PCT);
+
+        std::env::set_var(MIN_FREE_EPC_PCT_VAR, "101");
+        assert_eq!(min_free_epc_pct(), DEFAULT_MIN_FREE_EPC_PCT);
+        std::env::remove_var(MIN_FREE_EPC_PCT_VAR);
+    }
+}